@@ -1,41 +1,139 @@
-use anyhow::Result;
-use plonky2::hash::merkle_tree::MerkleTree;
+use std::fs;
+use std::path::Path;
+
+use anyhow::{anyhow, Result};
+use plonky2::field::field_types::{Field, PrimeField64};
+use plonky2::hash::hash_types::HashOut;
+use plonky2::hash::merkle_proofs::MerkleProof;
+use plonky2::hash::merkle_tree::MerkleCap;
 use plonky2::hash::poseidon::PoseidonHash;
 use plonky2::iop::witness::PartialWitness;
 use plonky2::plonk::circuit_builder::CircuitBuilder;
-use plonky2::plonk::circuit_data::{CircuitConfig, VerifierCircuitData};
+use plonky2::plonk::circuit_data::{
+    CircuitConfig, VerifierCircuitData, VerifierCircuitTarget, VerifierOnlyCircuitData,
+};
 use plonky2::plonk::config::Hasher;
 use plonky2::plonk::proof::ProofWithPublicInputs;
 
-use crate::signal::{Digest, Signal, C, F}; // Import constant values from Signal crate
+use crate::signal::{AggregateSignal, Digest, RlnSignal, Signal, C, F}; // Import constant values from Signal crate
 
-// AccessSet is a wrapper around a merkle tree (the leaves are the Pks)
-pub struct AccessSet(pub MerkleTree<F, PoseidonHash>);
+// AccessSet is a Merkle tree whose leaves are the member public keys (commitments).
+//
+// Rather than wrapping plonky2's immutable MerkleTree, we keep the full set of Poseidon layers
+// so members can be inserted, updated, or revoked without rebuilding the whole tree: a change to
+// one leaf only recomputes the O(height) path up to the root. The hashing matches plonky2's
+// MerkleTree with cap height 0 (leaf = hash_or_noop, internal node = two_to_one), so the
+// resulting root and opening proofs plug straight into verify_merkle_proof in the circuit.
+pub struct AccessSet {
+    leaves: Vec<Vec<F>>,
+    // layers[0] holds the leaf hashes; each subsequent layer halves the previous one; the last
+    // layer is the single-element cap, i.e. the Merkle root.
+    layers: Vec<Vec<HashOut<F>>>,
+}
 
 impl AccessSet {
 
+    // Build an access set from a vector of leaf commitments (its length must be a power of two).
+    pub fn new(leaves: Vec<Vec<F>>) -> Self {
+        let leaf_hashes: Vec<HashOut<F>> =
+            leaves.iter().map(|l| PoseidonHash::hash_or_noop(l)).collect();
+
+        let mut layers = vec![leaf_hashes];
+        while layers.last().unwrap().len() > 1 {
+            let prev = layers.last().unwrap();
+            let next: Vec<HashOut<F>> = prev
+                .chunks(2)
+                .map(|pair| PoseidonHash::two_to_one(pair[0], pair[1]))
+                .collect();
+            layers.push(next);
+        }
+
+        Self { leaves, layers }
+    }
+
+    // The canonical "empty" commitment used for unused and revoked leaves.
+    fn empty_commitment() -> Vec<F> {
+        vec![F::ZERO; 4]
+    }
+
+    // Number of leaves (the tree capacity).
+    pub fn num_leaves(&self) -> usize {
+        self.leaves.len()
+    }
+
+    // The current Merkle root (the single element sitting at the top layer).
+    pub fn root(&self) -> HashOut<F> {
+        self.layers.last().unwrap()[0]
+    }
+
+    // Opening proof for a leaf, built from the maintained layers in O(height).
+    pub fn prove(&self, index: usize) -> MerkleProof<F, PoseidonHash> {
+        let mut siblings = Vec::with_capacity(self.layers.len() - 1);
+        let mut idx = index;
+        for layer in &self.layers[..self.layers.len() - 1] {
+            siblings.push(layer[idx ^ 1]);
+            idx >>= 1;
+        }
+        MerkleProof { siblings }
+    }
+
+    // Recompute the path from a changed leaf up to the root, touching only O(height) nodes.
+    fn set_leaf(&mut self, index: usize, commitment: Vec<F>) {
+        self.layers[0][index] = PoseidonHash::hash_or_noop(&commitment);
+        self.leaves[index] = commitment;
+
+        let mut idx = index;
+        for level in 0..self.layers.len() - 1 {
+            let parent = idx >> 1;
+            let left = self.layers[level][parent << 1];
+            let right = self.layers[level][(parent << 1) | 1];
+            self.layers[level + 1][parent] = PoseidonHash::two_to_one(left, right);
+            idx = parent;
+        }
+    }
+
+    // Add a new member at the first empty leaf and return its index. This reclaims slots freed by
+    // `remove`, so revoking then re-registering reuses the hole rather than growing the tree.
+    // Panics if the tree is already full.
+    pub fn insert(&mut self, commitment: Vec<F>) -> usize {
+        let empty = Self::empty_commitment();
+        let index = self
+            .leaves
+            .iter()
+            .position(|l| *l == empty)
+            .expect("access set is full");
+        self.set_leaf(index, commitment);
+        index
+    }
+
+    // Replace the commitment at `index` (e.g. rotate a member's key).
+    pub fn update(&mut self, index: usize, commitment: Vec<F>) {
+        self.set_leaf(index, commitment);
+    }
+
+    // Revoke the member at `index` by resetting its leaf to the empty commitment.
+    pub fn remove(&mut self, index: usize) {
+        self.set_leaf(index, Self::empty_commitment());
+    }
+
     // Verify the signal
     // 1. Compute the public inputs for the proof. It generates a vector of public inputs by collecting elements
     // from different sources. Chained iterator to collect data. The public inputs are:
-    // 1.1. Merkle root of the access set (self) -> self.0.cap.0.iter().flat_map(|h| h.elements) extracts the "cap" of the merkle root
+    // 1.1. Merkle root of the access set (self) -> self.root().elements extracts the merkle root
     // "cap": holds merkle root data 
     // 1.2. Nullifier (signal.nullifier)
     // 1.3. Topic (topic)
     pub fn verify_signal(
         &self,
         topic: Digest,
+        message: &[F],
         signal: Signal,
-        verifier_data: &VerifierCircuitData<F, C, 2>,   //PLONK artifact to pre-process polynomials; it is a "verifier key" to verify the proof 
+        verifier_data: &VerifierCircuitData<F, C, 2>,   //PLONK artifact to pre-process polynomials; it is a "verifier key" to verify the proof
     ) -> Result<()> {
-        let public_inputs: Vec<F> = self
-            .0
-            .cap
-            .0
-            .iter()
-            .flat_map(|h| h.elements)
-            .chain(signal.nullifier)
-            .chain(topic)
-            .collect();
+        // Recompute the public inputs from the message so verification fails unless the proof
+        // authorizes exactly this message under this topic. After the root, nullifier and topic
+        // listed above come the signal hash and its element-wise square.
+        let public_inputs = self.semaphore_public_inputs(topic, message, signal.nullifier);
 
         // Verify the proof and the public inputs
         verifier_data.verify(ProofWithPublicInputs {
@@ -44,6 +142,27 @@ impl AccessSet {
         })
     }
 
+    // Rebuild the Semaphore public-input vector (root, nullifier, topic, sig_hash, sig_hash²) from
+    // the message. Shared by verify_signal and serialize_signal so both agree on the layout.
+    fn semaphore_public_inputs(&self, topic: Digest, message: &[F], nullifier: Digest) -> Vec<F> {
+        let sig_hash = PoseidonHash::hash_no_pad(message).elements;
+        let sig_hash_squared: Digest = [
+            sig_hash[0] * sig_hash[0],
+            sig_hash[1] * sig_hash[1],
+            sig_hash[2] * sig_hash[2],
+            sig_hash[3] * sig_hash[3],
+        ];
+
+        self.root()
+            .elements
+            .into_iter()
+            .chain(nullifier)
+            .chain(topic)
+            .chain(sig_hash)
+            .chain(sig_hash_squared)
+            .collect()
+    }
+
     // Issue a new signal to the Access Set
     // nullifier = H(sk, topic)
     // signal = nullifier + ZKP
@@ -52,36 +171,327 @@ impl AccessSet {
         &self,
         private_key: Digest,
         topic: Digest,
+        message: &[F],
         public_key_index: usize,
     ) -> Result<(Signal, VerifierCircuitData<F, C, 2>)> {
-        
-        // nullifier is the hash of a private key and a topic
-        let nullifier = PoseidonHash::hash_no_pad(&[private_key, topic].concat()).elements;
-        
-        // Plonky2 setup
-        // 1. Circuit config that allows recursion and zk to hide the secret key
-        // 2. Circuit builder: is how you build a circuit in plonky2; all the gadgets in plonky2 are methods of this builder
-        // 3. Partial witness: The witness in PLONK is basically a table; you don't have to fill all the wires/cells in the table manually;
-        // partial witness will partially fill the table with values and then the proving system will take care of generating the rest of the values
-        // using the gadgets; pw will just fill the data needed in the circuit
+
+        // Convenience one-shot path: build the circuit and immediately prove a single signal.
+        // When issuing many signals against the same access set, prefer building the circuit once
+        // with `build_circuit` and calling `prove_signal` repeatedly so keygen is amortized.
+        let circuit = self.build_circuit();
+        let signal = circuit.prove_signal(private_key, topic, message, public_key_index)?;
+        let verifier_data = VerifierCircuitData {
+            verifier_only: circuit.data.verifier_only.clone(),
+            common: circuit.data.common.clone(),
+        };
+
+        Ok((signal, verifier_data))
+    }
+
+    // Verify an RLN signal.
+    // The public-input vector mirrors rln_circuit: merkle_root, epoch, nullifier, x, y.
+    pub fn verify_rln_signal(
+        &self,
+        epoch: Digest,
+        signal: RlnSignal,
+        verifier_data: &VerifierCircuitData<F, C, 2>,
+    ) -> Result<()> {
+        let public_inputs: Vec<F> = self
+            .root()
+            .elements
+            .into_iter()
+            .chain(epoch)
+            .chain(signal.nullifier)
+            .chain([signal.x])
+            .chain(signal.y)
+            .collect();
+
+        verifier_data.verify(ProofWithPublicInputs {
+            proof: signal.proof,
+            public_inputs,
+        })
+    }
+
+    // Issue a rate-limited signal.
+    //
+    // a1 = Poseidon(sk || epoch); nullifier = Poseidon(a1). The share point is
+    // (x, y) with x = message_hash[0] and y[i] = sk[i] + a1[i]*x. Two signals in the same
+    // epoch publish two points on the same line, which leaks sk via recover_secret.
+    pub fn rln_signal(
+        &self,
+        private_key: Digest,
+        epoch: Digest,
+        message_hash: Digest,
+        public_key_index: usize,
+    ) -> Result<(RlnSignal, VerifierCircuitData<F, C, 2>)> {
+
+        // Line coefficients and nullifier, computed off-circuit to fill the witness / public share.
+        let a1 = PoseidonHash::hash_no_pad(&[private_key, epoch].concat()).elements;
+        let nullifier = PoseidonHash::hash_no_pad(&a1).elements;
+        let x = message_hash[0];
+        let mut y = [F::ZERO; 4];
+        for i in 0..4 {
+            y[i] = private_key[i] + a1[i] * x;
+        }
+
         let config = CircuitConfig::standard_recursion_zk_config();
         let mut builder = CircuitBuilder::new(config);
         let mut pw = PartialWitness::new();
 
-        // I want to define a circuit for the semaphore verification to get the ZKP
-        let targets = self.semaphore_circuit(&mut builder);
-        self.fill_semaphore_targets(&mut pw, private_key, topic, public_key_index, targets);
+        let targets = self.rln_circuit(&mut builder);
+        self.fill_rln_targets(&mut pw, private_key, epoch, message_hash, public_key_index, targets);
 
-        // Build the circuit and generate the proof with witness
         let data = builder.build();
         let proof = data.prove(pw)?;
 
         Ok((
-            Signal {
+            RlnSignal {
                 nullifier,
+                x,
+                y,
                 proof: proof.proof,
             },
             data.to_verifier_data(),
         ))
     }
 }
+
+impl AccessSet {
+
+    // Collapse a batch of Signals on a single topic into one constant-size recursive proof.
+    //
+    // Every inner Signal comes from the identical semaphore_circuit, so the inner verifier
+    // data is constant: we build that circuit once to obtain its CommonCircuitData and
+    // VerifierOnlyCircuitData, then embed the verifier cap as constants in a wrapper circuit.
+    // The wrapper adds one ProofWithPublicInputsTarget per child, verifies it against the
+    // inner data, constrains the Merkle root and topic to be equal across all children, and
+    // re-exposes the topic once plus each child's (root, nullifier) as public inputs.
+    pub fn aggregate_signals(
+        &self,
+        signals: &[Signal],
+        topic: Digest,
+    ) -> Result<(AggregateSignal, VerifierCircuitData<F, C, 2>)> {
+        let config = CircuitConfig::standard_recursion_zk_config();
+
+        // Re-synthesize the inner Semaphore circuit once to recover its constant circuit data.
+        let mut inner_builder = CircuitBuilder::new(config.clone());
+        let _ = self.semaphore_circuit(&mut inner_builder);
+        let inner_data = inner_builder.build::<C>();
+
+        // Wrapper circuit.
+        let mut builder = CircuitBuilder::new(config);
+        let mut pw = PartialWitness::new();
+
+        // The inner verifier data is constant across all children, so embed it as constants.
+        // Both the sigmas cap and the circuit digest are pinned: without the digest the wrapper
+        // would accept a proof from any circuit that happened to share the sigmas cap.
+        let inner_verifier_target = VerifierCircuitTarget {
+            constants_sigmas_cap: builder
+                .constant_merkle_cap(&inner_data.verifier_only.constants_sigmas_cap),
+            circuit_digest: builder.constant_hash(inner_data.verifier_only.circuit_digest),
+        };
+
+        // One verified inner proof per signal.
+        let proof_targets: Vec<_> = signals
+            .iter()
+            .map(|_| {
+                let pt = builder.add_virtual_proof_with_pis(&inner_data.common);
+                builder.verify_proof::<C>(pt.clone(), &inner_verifier_target, &inner_data.common);
+                pt
+            })
+            .collect();
+
+        // Inner public-input layout is root(4) || nullifier(4) || topic(4) || sig_hash(4) ||
+        // sig_hash_squared(4). Constrain the root
+        // and topic to agree with the first child, then re-expose topic once and every
+        // (root, nullifier) pair.
+        let first = proof_targets[0].public_inputs.clone();
+        builder.register_public_inputs(&first[8..12]); // topic
+        for pt in &proof_targets {
+            for i in 0..4 {
+                builder.connect(pt.public_inputs[i], first[i]); // root == root_0
+                builder.connect(pt.public_inputs[8 + i], first[8 + i]); // topic == topic_0
+            }
+            builder.register_public_inputs(&pt.public_inputs[0..4]); // root
+            builder.register_public_inputs(&pt.public_inputs[4..8]); // nullifier
+        }
+
+        // Witness each child proof together with its reconstructed public inputs.
+        for (pt, signal) in proof_targets.iter().zip(signals) {
+            let sig_hash_squared: Digest = [
+                signal.sig_hash[0] * signal.sig_hash[0],
+                signal.sig_hash[1] * signal.sig_hash[1],
+                signal.sig_hash[2] * signal.sig_hash[2],
+                signal.sig_hash[3] * signal.sig_hash[3],
+            ];
+            let public_inputs: Vec<F> = self
+                .root()
+                .elements
+                .into_iter()
+                .chain(signal.nullifier)
+                .chain(topic)
+                .chain(signal.sig_hash)
+                .chain(sig_hash_squared)
+                .collect();
+            pw.set_proof_with_pis_target(
+                pt,
+                &ProofWithPublicInputs {
+                    proof: signal.proof.clone(),
+                    public_inputs,
+                },
+            );
+        }
+
+        let data = builder.build();
+        let proof = data.prove(pw)?;
+
+        Ok((
+            AggregateSignal {
+                proof: proof.proof,
+            },
+            data.to_verifier_data(),
+        ))
+    }
+
+    // Verify an aggregate proof against the topic and the per-child roots and nullifiers.
+    // The public-input vector mirrors aggregate_signals: topic, then (root, nullifier) per child.
+    pub fn verify_aggregate(
+        &self,
+        topic: Digest,
+        agg_signal: AggregateSignal,
+        roots: &[Digest],
+        nullifiers: &[Digest],
+        verifier_data: &VerifierCircuitData<F, C, 2>,
+    ) -> Result<()> {
+        let public_inputs: Vec<F> = topic
+            .into_iter()
+            .chain(
+                roots
+                    .iter()
+                    .zip(nullifiers)
+                    .flat_map(|(r, n)| r.iter().copied().chain(n.iter().copied())),
+            )
+            .collect();
+
+        verifier_data.verify(ProofWithPublicInputs {
+            proof: agg_signal.proof,
+            public_inputs,
+        })
+    }
+
+    // Encode a signal to bytes for transport to a verifier process, using plonky2's own proof
+    // serialization. The full public-input vector is reconstructed and shipped with the proof, so
+    // the receiver can read the nullifier and signal hash back out of it; verify_signal still
+    // re-derives the public inputs from the topic and message.
+    pub fn serialize_signal(&self, topic: Digest, message: &[F], signal: &Signal) -> Vec<u8> {
+        let public_inputs = self.semaphore_public_inputs(topic, message, signal.nullifier);
+        ProofWithPublicInputs {
+            proof: signal.proof.clone(),
+            public_inputs,
+        }
+        .to_bytes()
+    }
+
+    // Reconstruct a signal from its byte encoding. The CommonCircuitData (from the verifier data)
+    // is required to parse the proof; the nullifier and signal hash are read back out of the
+    // decoded public inputs.
+    pub fn deserialize_signal(
+        &self,
+        bytes: &[u8],
+        verifier_data: &VerifierCircuitData<F, C, 2>,
+    ) -> Result<Signal> {
+        let pwpi = ProofWithPublicInputs::<F, C, 2>::from_bytes(bytes.to_vec(), &verifier_data.common)?;
+        let nullifier: Digest = pwpi.public_inputs[4..8].try_into().unwrap();
+        let sig_hash: Digest = pwpi.public_inputs[12..16].try_into().unwrap();
+        Ok(Signal {
+            nullifier,
+            sig_hash,
+            proof: pwpi.proof,
+        })
+    }
+
+    // Persist the verifier circuit data so a separate verifier process can load it. Only the
+    // VerifierOnlyCircuitData (the verifier cap and circuit digest) is written out, as raw field
+    // elements: the CommonCircuitData is deterministic for a given tree height and is
+    // reconstructed on load via `load_verifier_data`, which rebuilds the circuit to do so.
+    pub fn save_verifier_data(
+        path: impl AsRef<Path>,
+        verifier_data: &VerifierCircuitData<F, C, 2>,
+    ) -> Result<()> {
+        let vo = &verifier_data.verifier_only;
+        let mut out = Vec::new();
+        out.extend_from_slice(&(vo.constants_sigmas_cap.0.len() as u64).to_le_bytes());
+        for h in &vo.constants_sigmas_cap.0 {
+            write_digest(&mut out, h);
+        }
+        write_digest(&mut out, &vo.circuit_digest);
+        fs::write(path, out)?;
+        Ok(())
+    }
+
+    // Reload verifier circuit data previously written by `save_verifier_data`, pairing the stored
+    // verifier-only data with freshly rebuilt CommonCircuitData for this access set.
+    pub fn load_verifier_data(
+        &self,
+        path: impl AsRef<Path>,
+    ) -> Result<VerifierCircuitData<F, C, 2>> {
+        let bytes = fs::read(path)?;
+        let cap_len = u64::from_le_bytes(bytes[0..8].try_into().unwrap()) as usize;
+
+        let mut offset = 8;
+        let mut cap = Vec::with_capacity(cap_len);
+        for _ in 0..cap_len {
+            cap.push(read_digest(&bytes[offset..offset + 32]));
+            offset += 32;
+        }
+        let circuit_digest = read_digest(&bytes[offset..offset + 32]);
+
+        let verifier_only = VerifierOnlyCircuitData {
+            constants_sigmas_cap: MerkleCap(cap),
+            circuit_digest,
+        };
+        let common = self.build_circuit().data.common;
+        Ok(VerifierCircuitData {
+            verifier_only,
+            common,
+        })
+    }
+}
+
+// Append a digest to `out` as four little-endian u64 field elements.
+fn write_digest(out: &mut Vec<u8>, h: &HashOut<F>) {
+    for e in h.elements {
+        out.extend_from_slice(&e.to_canonical_u64().to_le_bytes());
+    }
+}
+
+// Read a digest back from 32 bytes written by `write_digest`.
+fn read_digest(bytes: &[u8]) -> HashOut<F> {
+    let mut elements = [F::ZERO; 4];
+    for (i, chunk) in bytes.chunks(8).take(4).enumerate() {
+        elements[i] = F::from_canonical_u64(u64::from_le_bytes(chunk.try_into().unwrap()));
+    }
+    HashOut { elements }
+}
+
+// Recover the private key a0 = sk from two RLN signals that reused the same nullifier
+// (i.e. the same member signaled twice in one epoch). Both points lie on the line
+// p(x) = a0 + a1*x, so per element i:
+//   a1[i] = (y2[i] - y1[i]) / (x2 - x1)
+//   a0[i] = y1[i] - a1[i] * x1
+//
+// The two signals must sit at distinct abscissae. Since x = message_hash[0], messages whose
+// hashes collide in the first field element (or the literal same message) yield x2 == x1 and the
+// line cannot be interpolated; this returns an error in that case rather than dividing by zero.
+pub fn recover_secret(signal_a: &RlnSignal, signal_b: &RlnSignal) -> Result<Digest> {
+    let dx_inv = (signal_b.x - signal_a.x)
+        .try_inverse()
+        .ok_or_else(|| anyhow!("cannot recover secret: the two signals share the same abscissa"))?;
+
+    let mut a0 = [F::ZERO; 4];
+    for i in 0..4 {
+        let a1 = (signal_b.y[i] - signal_a.y[i]) * dx_inv;
+        a0[i] = signal_a.y[i] - a1 * signal_a.x;
+    }
+    Ok(a0)
+}
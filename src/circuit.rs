@@ -1,3 +1,4 @@
+use anyhow::Result;
 use plonky2::field::field_types::Field;
 use plonky2::hash::hash_types::{HashOutTarget, MerkleCapTarget};
 use plonky2::hash::merkle_proofs::MerkleProofTarget;
@@ -5,18 +6,80 @@ use plonky2::hash::poseidon::PoseidonHash;
 use plonky2::iop::target::Target;
 use plonky2::iop::witness::{PartialWitness, Witness};
 use plonky2::plonk::circuit_builder::CircuitBuilder;
+use plonky2::plonk::circuit_data::{CircuitConfig, CircuitData, VerifierCircuitData};
+use plonky2::plonk::config::Hasher;
 
 use crate::access_set::AccessSet;
-use crate::signal::{Digest, F};
+use crate::signal::{Digest, Signal, C, F};
 
+#[derive(Clone)]
 pub struct SemaphoreTargets {
     merkle_root: HashOutTarget,
     topic: [Target; 4],
+    sig_hash: HashOutTarget,
+    sig_hash_squared: [Target; 4],
     merkle_proof: MerkleProofTarget,
     private_key: [Target; 4],
     public_key_index: Target,
 }
 
+// Targets for the rate-limiting-nullifier (RLN) circuit. On top of the membership check
+// it binds the secret to the line p(x) = a0 + a1*x, where a0 = sk and a1 = Poseidon(a0, epoch).
+pub struct RlnTargets {
+    merkle_root: HashOutTarget,
+    epoch: [Target; 4],
+    nullifier: HashOutTarget,
+    x: Target,
+    merkle_proof: MerkleProofTarget,
+    private_key: [Target; 4],
+    public_key_index: Target,
+}
+
+// A prebuilt, reusable Semaphore circuit.
+//
+// Building the circuit (synthesizing the gates and running keygen) is the expensive part and
+// does not depend on which member is signaling, so we do it once via AccessSet::build_circuit
+// and then amortize it across every signal. prove_signal only fills the partial witness and
+// calls prove on the already-built CircuitData. The VerifierCircuitData can be serialized and
+// shipped to verifiers instead of being handed back from each signal.
+pub struct SemaphoreCircuit<'a> {
+    access_set: &'a AccessSet,
+    pub data: CircuitData<F, C, 2>,
+    pub targets: SemaphoreTargets,
+    pub verifier_data: VerifierCircuitData<F, C, 2>,
+}
+
+impl<'a> SemaphoreCircuit<'a> {
+    // Issue a signal reusing the prebuilt circuit: only the witness is filled per call.
+    pub fn prove_signal(
+        &self,
+        private_key: Digest,
+        topic: Digest,
+        message: &[F],
+        public_key_index: usize,
+    ) -> Result<Signal> {
+        let nullifier = PoseidonHash::hash_no_pad(&[private_key, topic].concat()).elements;
+        let sig_hash = PoseidonHash::hash_no_pad(message).elements;
+
+        let mut pw = PartialWitness::new();
+        self.access_set.fill_semaphore_targets(
+            &mut pw,
+            private_key,
+            topic,
+            message,
+            public_key_index,
+            self.targets.clone(),
+        );
+
+        let proof = self.data.prove(pw)?;
+        Ok(Signal {
+            nullifier,
+            sig_hash,
+            proof: proof.proof,
+        })
+    }
+}
+
 impl AccessSet {
 
     // AccessSet is a binary merkle tree with n leaves, where n=2^h
@@ -24,7 +87,7 @@ impl AccessSet {
     // the binary representation of 2^h will always be a 1000.. ; where 0s indicate the power of 2 (thus h)
     // example: 2^h = 8 -> (binary) 1000 -> h=3
     pub fn tree_height(&self) -> usize {
-        self.0.leaves.len().trailing_zeros() as usize
+        self.num_leaves().trailing_zeros() as usize
     }
 
     pub fn semaphore_circuit(&self, builder: &mut CircuitBuilder<F, 2>) -> SemaphoreTargets {
@@ -41,6 +104,13 @@ impl AccessSet {
         builder.register_public_inputs(&nullifier.elements);
         let topic: [Target; 4] = builder.add_virtual_targets(4).try_into().unwrap();    // Tell the circuti to create 4 targets for that purpose and convert Vec<Target> into an array [Target; 4]
         builder.register_public_inputs(&topic);
+        // 4. Signal hash (the hash of the signaled message) and its square.
+        // Registering the hash as a public input binds the proof to one message; the square
+        // mirrors the original Semaphore signal-hash design and adds no binding of its own (see Gate 3).
+        let sig_hash = builder.add_virtual_hash();
+        builder.register_public_inputs(&sig_hash.elements);
+        let sig_hash_squared: [Target; 4] = builder.add_virtual_targets(4).try_into().unwrap();
+        builder.register_public_inputs(&sig_hash_squared);
 
         // Merkle proof (opening)
         let merkle_proof = MerkleProofTarget {
@@ -82,10 +152,21 @@ impl AccessSet {
             builder.connect(nullifier.elements[i], should_be_nullifier.elements[i]);
         }
 
+        // Gate 3: the message is already bound by registering sig_hash as a public input (the
+        // verifier recomputes it from the message). This squaring constraint adds no extra binding;
+        // it exists only so sig_hash is used in a gate and cannot be optimized away, matching the
+        // original Semaphore signal-hash design.
+        for i in 0..4 {
+            let sq = builder.mul(sig_hash.elements[i], sig_hash.elements[i]);
+            builder.connect(sig_hash_squared[i], sq);
+        }
+
         // Return all these targets
         SemaphoreTargets {
             merkle_root,
             topic,
+            sig_hash,
+            sig_hash_squared,
             merkle_proof,
             private_key,
             public_key_index,
@@ -98,27 +179,170 @@ impl AccessSet {
         pw: &mut PartialWitness<F>,
         private_key: Digest,
         topic: Digest,
+        message: &[F],
         public_key_index: usize,
         targets: SemaphoreTargets,
     ) {
         let SemaphoreTargets {
             merkle_root,
             topic: topic_target,
+            sig_hash: sig_hash_target,
+            sig_hash_squared: sig_hash_squared_target,
             merkle_proof: merkle_proof_target,
             private_key: private_key_target,
             public_key_index: public_key_index_target,
         } = targets;
 
-        // Set the targets 
-        pw.set_hash_target(merkle_root, self.0.cap.0[0]);
+        // Set the targets
+        pw.set_hash_target(merkle_root, self.root());
         pw.set_targets(&private_key_target, &private_key); // private_key is some field elements (Digest 4) and I can set them one-by-one here
         pw.set_targets(&topic_target, &topic);
+
+        // Hash the message and set the signal-hash targets together with their squares.
+        let sig_hash = PoseidonHash::hash_no_pad(message);
+        pw.set_hash_target(sig_hash_target, sig_hash);
+        for i in 0..4 {
+            pw.set_target(sig_hash_squared_target[i], sig_hash.elements[i] * sig_hash.elements[i]);
+        }
+
+        pw.set_target(
+            public_key_index_target,
+            F::from_canonical_usize(public_key_index),
+        );
+
+        let merkle_proof = self.prove(public_key_index);
+        for (ht, h) in merkle_proof_target
+            .siblings
+            .into_iter()
+            .zip(merkle_proof.siblings)
+        {
+            pw.set_hash_target(ht, h);
+        }
+    }
+
+    // Synthesize and run keygen for the Semaphore circuit once, returning a reusable handle.
+    // The resulting SemaphoreCircuit can prove many signals against this access set without
+    // re-synthesizing the circuit, and exposes the VerifierCircuitData to ship to verifiers.
+    pub fn build_circuit(&self) -> SemaphoreCircuit {
+        let config = CircuitConfig::standard_recursion_zk_config();
+        let mut builder = CircuitBuilder::new(config);
+        let targets = self.semaphore_circuit(&mut builder);
+        let data = builder.build::<C>();
+        let verifier_data = VerifierCircuitData {
+            verifier_only: data.verifier_only.clone(),
+            common: data.common.clone(),
+        };
+        SemaphoreCircuit {
+            access_set: self,
+            data,
+            targets,
+            verifier_data,
+        }
+    }
+
+    // Build the RLN (rate-limiting nullifier) circuit.
+    //
+    // The idea: the private key a0 = sk is the degree-0 coefficient of a line
+    // p(x) = a0 + a1*x, where the degree-1 coefficient is a1 = Poseidon(a0 || epoch).
+    // The nullifier is Poseidon(a1), so it is fixed per (sk, epoch): every signal in the
+    // same epoch evaluates the *same* line. Each signal publishes one share point
+    // (x, y) with x = message hash and y = a0 + a1*x, so two points in the same epoch
+    // uniquely determine the line and leak a0 = sk (see access_set::recover_secret).
+    //
+    // Public inputs, in order: merkle_root, epoch, nullifier, x, y.
+    pub fn rln_circuit(&self, builder: &mut CircuitBuilder<F, 2>) -> RlnTargets {
+
+        // #### 1 - Virtual targets and public inputs ####
+        let merkle_root = builder.add_virtual_hash();
+        builder.register_public_inputs(&merkle_root.elements);
+        let epoch: [Target; 4] = builder.add_virtual_targets(4).try_into().unwrap();
+        builder.register_public_inputs(&epoch);
+        let nullifier = builder.add_virtual_hash();
+        builder.register_public_inputs(&nullifier.elements);
+        // x is the share abscissa (a scalar over Goldilocks): the first element of the message hash.
+        let x = builder.add_virtual_target();
+        builder.register_public_input(x);
+
+        // Merkle proof (opening) and the witness needed to verify it.
+        let merkle_proof = MerkleProofTarget {
+            siblings: builder.add_virtual_hashes(self.tree_height()),
+        };
+        let private_key: [Target; 4] = builder.add_virtual_targets(4).try_into().unwrap();
+        let public_key_index = builder.add_virtual_target();
+        let public_key_index_bits = builder.split_le(public_key_index, self.tree_height());
+        let zero = builder.zero();
+
+        // #### 2 - Gates and wires ####
+
+        // Gate 1: the Merkle-membership check on Poseidon(a0, [0;4]), exactly as in semaphore_circuit.
+        builder.verify_merkle_proof::<PoseidonHash>(
+            [private_key, [zero; 4]].concat(),
+            &public_key_index_bits,
+            &MerkleCapTarget(vec![merkle_root]),
+            &merkle_proof,
+        );
+
+        // Gate 2: a1 = Poseidon(a0 || epoch).
+        let a1 = builder.hash_n_to_hash_no_pad::<PoseidonHash>([private_key, epoch].concat());
+
+        // Gate 3: nullifier = Poseidon(a1).
+        let should_be_nullifier =
+            builder.hash_n_to_hash_no_pad::<PoseidonHash>(a1.elements.to_vec());
+        for i in 0..4 {
+            builder.connect(nullifier.elements[i], should_be_nullifier.elements[i]);
+        }
+
+        // Gate 4: the share y = a0 + a1*x, evaluated per-element with the shared scalar x.
+        // Because x is shared by all four elements but differs across signals, the two resulting
+        // points per element are enough to interpolate a0 element-by-element off-circuit.
+        for i in 0..4 {
+            let a1_x = builder.mul(a1.elements[i], x);
+            let y_i = builder.add(private_key[i], a1_x);
+            builder.register_public_input(y_i);
+        }
+
+        RlnTargets {
+            merkle_root,
+            epoch,
+            nullifier,
+            x,
+            merkle_proof,
+            private_key,
+            public_key_index,
+        }
+    }
+
+    // Set the partial witness targets for the RLN circuit.
+    pub fn fill_rln_targets(
+        &self,
+        pw: &mut PartialWitness<F>,
+        private_key: Digest,
+        epoch: Digest,
+        message_hash: Digest,
+        public_key_index: usize,
+        targets: RlnTargets,
+    ) {
+        let RlnTargets {
+            merkle_root,
+            epoch: epoch_target,
+            nullifier: _,
+            x: x_target,
+            merkle_proof: merkle_proof_target,
+            private_key: private_key_target,
+            public_key_index: public_key_index_target,
+        } = targets;
+
+        pw.set_hash_target(merkle_root, self.root());
+        pw.set_targets(&private_key_target, &private_key);
+        pw.set_targets(&epoch_target, &epoch);
+        // The share abscissa is the first field element of the signaled message hash.
+        pw.set_target(x_target, message_hash[0]);
         pw.set_target(
             public_key_index_target,
             F::from_canonical_usize(public_key_index),
         );
 
-        let merkle_proof = self.0.prove(public_key_index);
+        let merkle_proof = self.prove(public_key_index);
         for (ht, h) in merkle_proof_target
             .siblings
             .into_iter()
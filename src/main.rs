@@ -3,7 +3,6 @@ use plonky2::plonk::config::PoseidonGoldilocksConfig;
 use plonky2::plonk::proof::Proof;
 use anyhow::{Ok, Result};
 use plonky2::field::field_types::Field;
-use plonky2::hash::merkle_tree::MerkleTree;
 use plonky2::hash::poseidon::PoseidonHash;
 use plonky2::plonk::config::Hasher;
 use std::time::Instant;
@@ -49,7 +48,7 @@ fn main() -> Result<()> {
 
     // Access Set is a Merkle tree with public_keys as leaves
     // leaves must be a Vec<Vec<F>>
-    let access_set = AccessSet(MerkleTree::new(public_keys, 0));
+    let access_set = AccessSet::new(public_keys);
 
     // Prove that the 12-th private key is in the tree
     let i = 12;
@@ -57,19 +56,26 @@ fn main() -> Result<()> {
 
     println!("topic: {:?}", topic);
 
-    // Prover: make the signal and the verifier circuit data
+    // The message being signaled (e.g. a vote choice) is bound into the proof.
+    let message: [F; 4] = F::rand_arr();
+
+    // Prover: build the circuit once (amortized keygen), then prove a signal reusing it.
+    let now = Instant::now();
+    let circuit = access_set.build_circuit();
+    let time_build = now.elapsed();
+
     let now = Instant::now();
-    let (signal, vd) = access_set.make_signal(private_keys[i], topic, i)?;
+    let signal = circuit.prove_signal(private_keys[i], topic, &message, i)?;
     let time_prove = now.elapsed();
 
     // Verifier: verify the signal
     // Proof: "I show you that PK=12 have voted for this topic given the nullifier you have"
     let now = Instant::now();
-    access_set.verify_signal(topic, signal, &vd).unwrap();
+    access_set.verify_signal(topic, &message, signal, &circuit.verifier_data).unwrap();
     let time_verify = now.elapsed();
-    
+
     println!(
-        "time_prove={time_prove:?} time_verify={time_verify:?}"
+        "time_build={time_build:?} time_prove={time_prove:?} time_verify={time_verify:?}"
     );
 
     Ok(())
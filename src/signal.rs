@@ -8,9 +8,35 @@ pub type C = PoseidonGoldilocksConfig;
 pub type PlonkyProof = Proof<F, PoseidonGoldilocksConfig, 2>;   // Plonky2 proof struct with extension 2
 
 #[derive(Debug, Clone)]
-// Signal contains a nullifier and a plonky proof
+// Signal contains a nullifier, the hash of the signaled message, and a plonky proof.
+// sig_hash binds the proof to exactly one message under the topic (see semaphore_circuit),
+// and is carried here so a relayer can reconstruct the proof's public inputs.
+// Transport over the wire is handled by AccessSet::serialize_signal / deserialize_signal.
 pub struct Signal {
     pub nullifier: Digest,
+    pub sig_hash: Digest,
+    pub proof: PlonkyProof,
+}
+
+#[derive(Debug, Clone)]
+// RlnSignal is the rate-limiting-nullifier variant of a Signal.
+// On top of the usual nullifier + proof it carries the share point (x, y) that the
+// circuit exposed as public inputs: x is the share abscissa (the first element of the
+// signaled message hash) and y is the line evaluation y = a0 + a1*x computed per-element.
+// Two RlnSignals issued in the same epoch with the same nullifier lie on the same line,
+// so `recover_secret` can interpolate back to a0 = sk. See AccessSet::rln_signal.
+pub struct RlnSignal {
+    pub nullifier: Digest,
+    pub x: F,
+    pub y: Digest,
+    pub proof: PlonkyProof,
+}
+
+#[derive(Debug, Clone)]
+// AggregateSignal is a single recursive proof that attests to a batch of inner Signals.
+// It only carries the wrapper proof; the public inputs (topic, and the per-child roots and
+// nullifiers) are supplied to verify_aggregate, mirroring how verify_signal rebuilds them.
+pub struct AggregateSignal {
     pub proof: PlonkyProof,
 }
 
@@ -18,7 +44,6 @@ pub struct Signal {
 mod tests {
     use anyhow::Result;
     use plonky2::field::field_types::Field;
-    use plonky2::hash::merkle_tree::MerkleTree;
     use plonky2::hash::poseidon::PoseidonHash;
     use plonky2::plonk::config::Hasher;
 
@@ -45,12 +70,144 @@ mod tests {
             })
             .collect();
 
-        let access_set = AccessSet(MerkleTree::new(public_keys, 0));    // Compute the access set
+        let access_set = AccessSet::new(public_keys);    // Compute the access set
 
         let i = 12;                                 // Generate a proof for the 12-th private key
         let topic = F::rand_arr();   // generate a random topic
+        let message = F::rand_arr(); // the signaled message bound into the proof
+
+        let (signal, vd) = access_set.make_signal(private_keys[i], topic, &message, i)?;  // make the signal
+        access_set.verify_signal(topic, &message, signal, &vd)           // verify the signal
+    }
+
+    // Build a small access set together with its private keys, for the feature tests below.
+    fn small_access_set(n: usize) -> (Vec<Digest>, AccessSet) {
+        let private_keys: Vec<Digest> = (0..n).map(|_| F::rand_arr()).collect();
+        let public_keys: Vec<Vec<F>> = private_keys
+            .iter()
+            .map(|&sk| {
+                PoseidonHash::hash_no_pad(&[sk, [F::ZERO; 4]].concat())
+                    .elements
+                    .to_vec()
+            })
+            .collect();
+        (private_keys, AccessSet::new(public_keys))
+    }
+
+    #[test]
+    fn test_aggregate() -> Result<()> {
+        let (private_keys, access_set) = small_access_set(1 << 5);
+        let topic = F::rand_arr();
+
+        // Two distinct members signal on the same topic with different messages.
+        let (signal_a, _) = access_set.make_signal(private_keys[3], topic, &F::rand_arr(), 3)?;
+        let (signal_b, _) = access_set.make_signal(private_keys[7], topic, &F::rand_arr(), 7)?;
+        let signals = vec![signal_a.clone(), signal_b.clone()];
+
+        // Collapse them into a single recursive proof and verify it.
+        let (agg_signal, vd) = access_set.aggregate_signals(&signals, topic)?;
+        let root = access_set.root().elements;
+        access_set.verify_aggregate(
+            topic,
+            agg_signal,
+            &[root, root],
+            &[signal_a.nullifier, signal_b.nullifier],
+            &vd,
+        )
+    }
+
+    #[test]
+    fn test_rln_recovery() -> Result<()> {
+        use crate::access_set::recover_secret;
+
+        let (private_keys, access_set) = small_access_set(1 << 5);
+        let sk = private_keys[5];
+        let epoch = F::rand_arr();
+
+        // The same member signals twice in one epoch with two different messages.
+        let (signal_1, vd) = access_set.rln_signal(sk, epoch, F::rand_arr(), 5)?;
+        let (signal_2, _) = access_set.rln_signal(sk, epoch, F::rand_arr(), 5)?;
+
+        // The nullifier is fixed per (sk, epoch), so both shares lie on the same line.
+        assert_eq!(signal_1.nullifier, signal_2.nullifier);
+
+        // The two shares leak the private key.
+        assert_eq!(recover_secret(&signal_1, &signal_2)?, sk);
+
+        // A well-formed signal verifies under its epoch, and fails under any other.
+        access_set.verify_rln_signal(epoch, signal_1.clone(), &vd)?;
+        assert!(access_set
+            .verify_rln_signal(F::rand_arr(), signal_1, &vd)
+            .is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_dynamic_membership() {
+        // Start from an all-empty tree of capacity 8.
+        let empty = vec![F::ZERO; 4];
+        let mut access_set = AccessSet::new(vec![empty.clone(); 8]);
+
+        let a = F::rand_arr().to_vec();
+        let b = F::rand_arr().to_vec();
+        let c = F::rand_arr().to_vec();
+
+        // insert fills the lowest empty slot.
+        assert_eq!(access_set.insert(a.clone()), 0);
+        assert_eq!(access_set.insert(b.clone()), 1);
+        assert_eq!(access_set.insert(c.clone()), 2);
+
+        // The incrementally maintained root matches a freshly built tree with the same leaves.
+        let expected = AccessSet::new(vec![
+            a.clone(),
+            b.clone(),
+            c.clone(),
+            empty.clone(),
+            empty.clone(),
+            empty.clone(),
+            empty.clone(),
+            empty.clone(),
+        ]);
+        assert_eq!(access_set.root(), expected.root());
+
+        // Revoking a middle member frees its slot, and the next insert reclaims it.
+        access_set.remove(1);
+        assert_eq!(access_set.insert(c.clone()), 1);
+
+        // update replaces a leaf in place.
+        access_set.update(0, b.clone());
+        let expected = AccessSet::new(vec![
+            b.clone(),
+            c.clone(),
+            c.clone(),
+            empty.clone(),
+            empty.clone(),
+            empty.clone(),
+            empty.clone(),
+            empty,
+        ]);
+        assert_eq!(access_set.root(), expected.root());
+    }
+
+    #[test]
+    fn test_signal_serialization() -> Result<()> {
+        let (private_keys, access_set) = small_access_set(1 << 5);
+        let topic = F::rand_arr();
+        let message = F::rand_arr();
+        let (signal, vd) = access_set.make_signal(private_keys[9], topic, &message, 9)?;
+
+        // Round-trip the signal through its byte encoding, then verify the decoded signal.
+        let bytes = access_set.serialize_signal(topic, &message, &signal);
+        let decoded = access_set.deserialize_signal(&bytes, &vd)?;
+        assert_eq!(decoded.nullifier, signal.nullifier);
+        assert_eq!(decoded.sig_hash, signal.sig_hash);
+        access_set.verify_signal(topic, &message, decoded, &vd)?;
 
-        let (signal, vd) = access_set.make_signal(private_keys[i], topic, i)?;  // make the signal
-        access_set.verify_signal(topic, signal, &vd)           // verify the signal
+        // Round-trip the verifier data through disk, then verify a signal with the reloaded copy.
+        let path = std::env::temp_dir().join("semaphore_verifier_data_roundtrip.bin");
+        AccessSet::save_verifier_data(&path, &vd)?;
+        let reloaded = access_set.load_verifier_data(&path)?;
+        let (signal2, _) = access_set.make_signal(private_keys[9], topic, &message, 9)?;
+        access_set.verify_signal(topic, &message, signal2, &reloaded)
     }
 }